@@ -0,0 +1,76 @@
+use super::Backend;
+use crate::env;
+use std::env as stdenv;
+use std::path::Path;
+use std::process::Command;
+
+/// The original (and default) backend: shells out to `direnv`.
+pub struct Direnv;
+
+impl Direnv {
+    fn command(&self) -> Command {
+        Command::new("direnv")
+    }
+}
+
+impl Backend for Direnv {
+    fn version_check(&self) -> Result<(), String> {
+        // Older versions of direnv have bugs that prevent building from
+        // working correctly.
+        let version_min = semver::Version::new(2, 20, 1);
+        let mut command = self.command();
+        command.arg("version");
+        let command_output = command.output().map_err(|err| format!("{}", err))?;
+        let version_string = String::from_utf8_lossy(&command_output.stdout);
+        let version = semver::Version::parse(&version_string)
+            .map_err(|err| format!("could not parse version {:?}: {}", version_string, err))?;
+        if version < version_min {
+            Err(format!(
+                "direnv is too old ({}); upgrade to {} or later (hint: use `nix-env -i direnv`)",
+                version, version_min,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn prepare(&self, build_dir: &Path) -> Result<(), String> {
+        let mut command = self.command();
+        command.arg("allow").arg(build_dir);
+        let status = command.status().map_err(|err| format!("{}", err))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err("could not enable direnv".into())
+        }
+    }
+
+    fn command_to_dump_inside(
+        &self,
+        dump_path: &Path,
+        build_dir: &Path,
+        _env_outside: &env::Env,
+    ) -> Command {
+        let mut command = self.command();
+        command
+            .arg("exec")
+            .arg(build_dir)
+            .arg(current_exe())
+            .arg(crate::cmds::env::NAME)
+            .arg("--dump")
+            .arg(dump_path);
+        command
+    }
+
+    fn env_prefixes_to_exclude(&self) -> Vec<&'static [u8]> {
+        vec![b"DIRENV_"]
+    }
+
+    fn vars_to_preserve(&self) -> Vec<&'static [u8]> {
+        vec![b"DIRENV_WATCHES"]
+    }
+}
+
+fn current_exe() -> std::path::PathBuf {
+    stdenv::current_exe().expect("could not determine path to the current executable")
+}
@@ -0,0 +1,39 @@
+pub mod direnv;
+
+use crate::env;
+use std::path::Path;
+use std::process::Command;
+
+/// A pluggable way of preparing and capturing a project's development
+/// environment. `direnv` is the only implementation today, but this is the
+/// seam third parties (or we) add others through, e.g. a raw `nix develop`
+/// or flake backend, or one that just sources a script.
+pub trait Backend {
+    /// Check that the backend's tooling is installed and new enough to work
+    /// correctly.
+    fn version_check(&self) -> Result<(), String>;
+
+    /// Do whatever setup the backend needs before its environment can be
+    /// captured, e.g. `direnv allow`.
+    fn prepare(&self, build_dir: &Path) -> Result<(), String>;
+
+    /// Build the command that dumps the environment as seen *inside* the
+    /// project (i.e. after the backend has set it up) to `dump_path`.
+    fn command_to_dump_inside(
+        &self,
+        dump_path: &Path,
+        build_dir: &Path,
+        env_outside: &env::Env,
+    ) -> Command;
+
+    /// Environment variable prefixes that belong to the backend itself
+    /// rather than the project, and so should be excluded from diffs.
+    fn env_prefixes_to_exclude(&self) -> Vec<&'static [u8]>;
+
+    /// Exact environment variable names the backend relies on internally,
+    /// e.g. direnv's own watch bookkeeping. `hook` adds the outside
+    /// environment's value of the first such variable it finds to the
+    /// parent-environment diff; a name that also matches one of
+    /// `env_prefixes_to_exclude` is still dropped when that diff is dumped.
+    fn vars_to_preserve(&self) -> Vec<&'static [u8]>;
+}
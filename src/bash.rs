@@ -0,0 +1,23 @@
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+
+/// Single-quote `value` so it can be dropped verbatim into a Bash script.
+///
+/// Single quotes are the only form of Bash quoting that doesn't interpret
+/// anything inside it, which makes it the safe choice for values (paths,
+/// environment variable contents, ...) that may contain arbitrary bytes.
+pub fn escape<T: AsRef<OsStr>>(value: T) -> Vec<u8> {
+    let bytes = value.as_ref().as_bytes();
+    let mut out = Vec::with_capacity(bytes.len() + 2);
+    out.push(b'\'');
+    for &byte in bytes {
+        if byte == b'\'' {
+            // End the quoted string, emit an escaped quote, reopen it.
+            out.extend(b"'\\''");
+        } else {
+            out.push(byte);
+        }
+    }
+    out.push(b'\'');
+    out
+}
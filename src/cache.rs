@@ -0,0 +1,261 @@
+use crate::env;
+use crate::sums;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Identifies a file as a `firstaide` cache entry, distinguishing it from
+/// e.g. a truncated or foreign file left behind by something else.
+const MAGIC: &[u8; 8] = b"FACACHE\0";
+
+/// Bumped whenever the shape of `Cache` changes in a way that makes old
+/// entries unreadable. A mismatch here means the entry is from an older (or
+/// newer) version of `firstaide`, not that it's corrupt.
+const SCHEMA_VERSION: u32 = 1;
+
+const CHECKSUM_LEN: usize = 32;
+const HEADER_LEN: usize = MAGIC.len() + 4 + CHECKSUM_LEN;
+
+/// The result of a `build`, persisted to disk so that `hook` can export it
+/// again without re-running the build every time a shell starts. Stored
+/// under `cache_dir/envs/<key>`, where `<key>` is `self.sums.key()` -- so
+/// loading by key already guarantees a checksum match, and switching back to
+/// a previously-built state of the tree is a cache hit rather than a rebuild.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cache {
+    pub diff: env::Diff,
+    pub sums: sums::Checksums,
+    /// Seconds since the Unix epoch at which this cache was built. `new`
+    /// always records it, whether or not a TTL is configured; it is `None`
+    /// only if the system clock read earlier than the Unix epoch at build
+    /// time. bincode isn't self-describing, so a payload from a version of
+    /// `Cache` that lacked this field wouldn't decode into `None`, it would
+    /// fail to decode at all. Any future change to this struct's shape must
+    /// bump `SCHEMA_VERSION` so `load` rejects mismatched entries via the
+    /// header (as `Error::Stale`) before bincode ever sees the payload,
+    /// rather than relying on serde defaults to paper over it.
+    pub built_at: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Bincode(bincode::Error),
+    /// The entry's schema version doesn't match ours. Most likely an older
+    /// (or newer) build of `firstaide` wrote it; treat it the same as a
+    /// missing entry and let it be rebuilt.
+    Stale(u32),
+    /// The entry's bytes don't match its stored checksum, e.g. a partial
+    /// write left behind by a crash. Treat it the same as a missing entry.
+    Corrupt,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+        match self {
+            Io(err) => write!(f, "input/output error: {}", err),
+            Bincode(err) => write!(f, "could not decode cache entry: {}", err),
+            Stale(version) => write!(
+                f,
+                "cache entry is schema version {}, expected {}",
+                version, SCHEMA_VERSION
+            ),
+            Corrupt => write!(f, "cache entry is corrupt"),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(error: bincode::Error) -> Self {
+        Error::Bincode(error)
+    }
+}
+
+impl Cache {
+    pub fn new(diff: env::Diff, sums: sums::Checksums) -> Cache {
+        Cache {
+            diff,
+            sums,
+            built_at: now(),
+        }
+    }
+
+    /// Whether this cache is still within its configured `max_age`. Checksum
+    /// freshness isn't this method's concern any more: a `Cache` loaded by
+    /// key (see [`load`]) already matches the current tree by construction.
+    pub fn is_within_ttl(&self, max_age: Option<Duration>) -> bool {
+        match (self.built_at, max_age) {
+            (_, None) => true,
+            // No recorded build time (the clock read before the Unix epoch
+            // when this cache was built); don't treat that as an expiry.
+            (None, Some(_)) => true,
+            (Some(built_at), Some(max_age)) => match now() {
+                Some(now) => now.saturating_sub(built_at) <= max_age.as_secs(),
+                None => true,
+            },
+        }
+    }
+
+    /// Serializes `self` behind a small header (a magic tag, the schema
+    /// version, and a checksum of the payload bytes) so that `load` can tell
+    /// a stale-but-intact entry apart from a corrupt one.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let payload = bincode::serialize(self)?;
+        let checksum: [u8; CHECKSUM_LEN] = Sha256::digest(&payload).into();
+
+        let mut file = fs::File::create(path)?;
+        file.write_all(MAGIC)?;
+        file.write_all(&SCHEMA_VERSION.to_le_bytes())?;
+        file.write_all(&checksum)?;
+        file.write_all(&payload)?;
+        Ok(())
+    }
+
+    /// Load a cache entry, touching its modification time so the keyed store
+    /// can use it as an LRU signal for eviction. Returns [`Error::Stale`] if
+    /// the entry was written by a different schema version, or
+    /// [`Error::Corrupt`] if its bytes don't match its stored checksum --
+    /// either way, callers should treat this the same as a missing entry.
+    pub fn load(path: impl AsRef<Path>) -> Result<Cache, Error> {
+        let path = path.as_ref();
+        let mut bytes = Vec::new();
+        fs::File::open(path)?.read_to_end(&mut bytes)?;
+
+        if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(Error::Corrupt);
+        }
+
+        let mut version_bytes = [0u8; 4];
+        version_bytes.copy_from_slice(&bytes[MAGIC.len()..MAGIC.len() + 4]);
+        let version = u32::from_le_bytes(version_bytes);
+        if version != SCHEMA_VERSION {
+            return Err(Error::Stale(version));
+        }
+
+        let checksum_start = MAGIC.len() + 4;
+        let payload = &bytes[checksum_start + CHECKSUM_LEN..];
+        let stored_checksum = &bytes[checksum_start..checksum_start + CHECKSUM_LEN];
+        let actual_checksum: [u8; CHECKSUM_LEN] = Sha256::digest(payload).into();
+        if actual_checksum[..] != stored_checksum[..] {
+            return Err(Error::Corrupt);
+        }
+
+        let cache = bincode::deserialize(payload)?;
+        let _ = touch(path);
+        Ok(cache)
+    }
+}
+
+/// The most recently used cache entry under `envs_dir`, if any, skipping
+/// over entries that fail to decode. Used as the last-known-good fallback
+/// when the current tree's checksums don't match any entry exactly.
+pub fn most_recently_used(envs_dir: &Path) -> Option<Cache> {
+    entries_by_recency(envs_dir)
+        .into_iter()
+        .find_map(|path| Cache::load(&path).ok())
+}
+
+/// Remove all but the `keep` most recently used entries under `envs_dir`.
+pub fn evict_lru(envs_dir: &Path, keep: usize) -> io::Result<()> {
+    for path in entries_by_recency(envs_dir).into_iter().skip(keep) {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn entries_by_recency(envs_dir: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<(PathBuf, SystemTime)> = match fs::read_dir(envs_dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let modified = entry.metadata().ok()?.modified().ok()?;
+                Some((entry.path(), modified))
+            })
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+    entries.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+    entries.into_iter().map(|(path, _)| path).collect()
+}
+
+fn touch(path: &Path) -> io::Result<()> {
+    fs::OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+fn now() -> Option<u64> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_cache() -> Cache {
+        Cache::new(env::Diff::default(), sums::Checksums::default())
+    }
+
+    #[test]
+    fn round_trips_through_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("entry");
+        let cache = sample_cache();
+
+        cache.save(&path).unwrap();
+        let loaded = Cache::load(&path).unwrap();
+
+        assert_eq!(loaded.built_at, cache.built_at);
+    }
+
+    #[test]
+    fn rejects_a_payload_with_a_mismatched_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("entry");
+        sample_cache().save(&path).unwrap();
+
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff; // Flip a bit somewhere in the payload.
+        fs::write(&path, &bytes).unwrap();
+
+        assert!(matches!(Cache::load(&path), Err(Error::Corrupt)));
+    }
+
+    #[test]
+    fn rejects_a_payload_from_a_different_schema_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("entry");
+        sample_cache().save(&path).unwrap();
+
+        let mut bytes = fs::read(&path).unwrap();
+        let version_start = MAGIC.len();
+        bytes[version_start..version_start + 4]
+            .copy_from_slice(&(SCHEMA_VERSION + 1).to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+
+        match Cache::load(&path) {
+            Err(Error::Stale(version)) => assert_eq!(version, SCHEMA_VERSION + 1),
+            other => panic!("expected Error::Stale, got {:?}", other),
+        }
+    }
+}
@@ -7,6 +7,7 @@ use spinners::{Spinner, Spinners};
 use std::fmt;
 use std::fs;
 use std::io;
+use std::time::Instant;
 use tempfile;
 
 pub const NAME: &str = "build";
@@ -16,12 +17,12 @@ type Result = std::result::Result<u8, Error>;
 pub enum Error {
     Config(config::Error),
     Io(io::Error),
-    DirEnv(String),
+    Backend(String),
     EnvOutsideCapture,
     EnvOutsideDecode(bincode::Error),
     EnvInsideCapture,
     EnvInsideDecode(bincode::Error),
-    Cache(bincode::Error),
+    Cache(cache::Error),
 }
 
 impl fmt::Display for Error {
@@ -30,7 +31,7 @@ impl fmt::Display for Error {
         match self {
             Config(err) => write!(f, "{}", err),
             Io(err) => write!(f, "input/output error: {}", err),
-            DirEnv(message) => write!(f, "direnv broke: {}", message),
+            Backend(message) => write!(f, "backend error: {}", message),
             EnvOutsideCapture => write!(f, "could not capture outside environment"),
             EnvOutsideDecode(err) => write!(f, "problem decoding outside environment: {}", err),
             EnvInsideCapture => write!(f, "could not capture inside environment"),
@@ -67,36 +68,68 @@ pub fn run(args: &clap::ArgMatches) -> Result {
     build(config)
 }
 
-fn spin<F, T>(f: F) -> T
+/// Run `f` as a named, timed phase of the build: on a TTY, show a spinner
+/// labelled with `name` and print how long it took once it's done; off a
+/// TTY (e.g. in CI logs), skip the spinner and log a single structured line
+/// with the same information instead. A phase that returns `Err` is
+/// reported as failed rather than done, so a glance at the output shows
+/// where a build actually stopped rather than a row of misleading
+/// successes right above the error that aborted it.
+fn phase<F, T, E>(name: &str, f: F) -> std::result::Result<T, E>
 where
-    F: FnOnce() -> T,
+    F: FnOnce() -> std::result::Result<T, E>,
 {
-    if atty::is(atty::Stream::Stdout) {
-        let spinner = Spinner::new(Spinners::Dots, "".into());
+    let is_tty = atty::is(atty::Stream::Stdout);
+    let start = Instant::now();
+    let result = if is_tty {
+        let spinner = Spinner::new(Spinners::Dots, name.into());
         let result = f();
         spinner.stop();
-        print!("\x08\x08"); // Backspace over the spinner.
+        // Return to the start of the spinner's line and clear it, rather
+        // than backspacing a fixed number of columns -- the spinner's own
+        // text is as long as `name`, which varies per phase.
+        print!("\r\x1b[2K");
         result
     } else {
         f()
+    };
+    let elapsed = start.elapsed().as_secs_f64();
+    match (&result, is_tty) {
+        (Ok(_), true) => println!("{} ... done in {:.1}s.", name, elapsed),
+        (Ok(_), false) => log::info!("{} ... done in {:.1}s.", name, elapsed),
+        (Err(_), true) => println!("{} ... failed after {:.1}s.", name, elapsed),
+        (Err(_), false) => log::warn!("{} ... failed after {:.1}s.", name, elapsed),
     }
+    result
 }
 
 fn build(config: config::Config) -> Result {
-    // 0. Check `direnv` is new enough. Older versions have bugs that prevent
-    // building from working correctly.
-    check_direnv_version(&config).map_err(Error::DirEnv)?;
-
-    // 1. Allow `direnv`.
-    log::info!("Allow direnv in {:?}.", &config.build_dir);
-    if !config.command_to_allow_direnv().status()?.success() {
-        return Err(Error::DirEnv("could not enable direnv".into()));
-    }
+    let backend = config.backend();
+
+    // 0. Check the backend's tooling is new enough. Older direnv versions,
+    // for instance, have bugs that prevent building from working correctly.
+    backend.version_check().map_err(Error::Backend)?;
+
+    // 1. Let the backend prepare the build dir (e.g. `direnv allow`).
+    log::info!("Prepare backend in {:?}.", &config.build_dir);
+    backend.prepare(&config.build_dir).map_err(Error::Backend)?;
 
     // 2. Create output directory.
     log::info!("Create cache dir at {:?}.", &config.cache_dir);
     fs::create_dir_all(&config.cache_dir)?;
 
+    // Bail out (without error) rather than race a build already in flight.
+    // This matters for the `refresh = "background"` hook mode, where a
+    // `direnv reload` can be triggered again before the previous background
+    // build has finished.
+    let _lock = match BuildLock::acquire(config.cache_dir.join("build.lock"))? {
+        Some(lock) => lock,
+        None => {
+            log::info!("A build is already in progress; exiting.");
+            return Ok(0);
+        }
+    };
+
     // Setting up additional OS pipes for subprocesses to communicate back to us
     // is not well supported in the Rust standard library, so we use files in a
     // temporary directory instead.
@@ -104,8 +137,7 @@ fn build(config: config::Config) -> Result {
     let temp_path = temp_dir.path().to_owned();
 
     // 3a. Capture outside environment.
-    log::info!("Capture outside environment.");
-    let env_outside: env::Env = spin(|| {
+    let env_outside: env::Env = phase("Capture outside environment", || {
         let dump_path = temp_path.join("outside");
         let mut dump_cmd = config.command_to_dump_env_outside(&dump_path);
         log::debug!("{:?}", dump_cmd);
@@ -120,10 +152,11 @@ fn build(config: config::Config) -> Result {
     })?;
 
     // 3b. Capture inside environment.
-    log::info!("Capture inside environment (may involve a full build).");
-    let env_inside: env::Env = spin(|| {
+    let inside_phase = "Capture inside environment (may involve a full build)";
+    let env_inside: env::Env = phase(inside_phase, || {
         let dump_path = temp_path.join("inside");
-        let mut dump_cmd = config.command_to_dump_env_inside(&dump_path, &env_outside);
+        let mut dump_cmd =
+            backend.command_to_dump_inside(&dump_path, &config.build_dir, &env_outside);
         log::debug!("{:?}", dump_cmd);
         let mut dump_proc = dump_cmd.spawn()?;
         if !dump_proc.wait()?.success() {
@@ -144,35 +177,131 @@ fn build(config: config::Config) -> Result {
     let env_diff = env::diff(&env_outside, &env_inside);
 
     // 5. Calculate checksums.
-    log::info!("Calculate file checksums.");
-    let checksums = spin(|| sums::Checksums::from(&config.watch_files()?))?;
+    let checksums = phase("Calculate file checksums", || {
+        sums::Checksums::from(&config.watch_files()?)
+    })?;
 
-    // 6. Write out cache.
+    // 6. Write out cache, keyed by the checksums that produced it, and evict
+    // anything beyond the configured number of entries.
     log::info!("Write out cache.");
-    let cache = cache::Cache {
-        diff: env_diff,
-        sums: checksums,
-    };
-    cache.save(config.cache_file()).map_err(Error::Cache)?;
+    let key = checksums.key();
+    let cache = cache::Cache::new(env_diff, checksums);
+    cache.save(config.cache_entry(&key)).map_err(Error::Cache)?;
+    cache::evict_lru(&config.cache_envs_dir(), config.cache_entries)?;
 
     // Done.
     Ok(0)
 }
 
-fn check_direnv_version(config: &config::Config) -> std::result::Result<(), String> {
-    let version_min = semver::Version::new(2, 20, 1);
-    let mut command = config.command_direnv();
-    command.arg("version");
-    let command_output = command.output().map_err(|err| format!("{}", err))?;
-    let version_string = String::from_utf8_lossy(&command_output.stdout);
-    let version = semver::Version::parse(&version_string)
-        .map_err(|err| format!("could not parse version {:?}: {}", version_string, err))?;
-    if version < version_min {
-        Err(format!(
-            "direnv is too old ({}); upgrade to {} or later (hint: use `nix-env -i direnv`)",
-            version, version_min,
-        ))
-    } else {
-        Ok(())
+/// Locks older than this are assumed to have been left behind by a process
+/// that died without cleaning up (OOM-killed, `kill -9`, a reboot mid-build)
+/// rather than a build that's still genuinely in progress -- no real build
+/// should ever take this long.
+const STALE_LOCK_AGE: std::time::Duration = std::time::Duration::from_secs(6 * 60 * 60);
+
+/// An exclusively-held marker file that prevents two builds from running
+/// against the same cache dir at once. Acquired with `O_CREAT|O_EXCL`
+/// semantics so two processes racing to create it can never both succeed.
+/// A lock file older than [`STALE_LOCK_AGE`] is treated as abandoned and
+/// reclaimed, so a build that died without unwinding can't wedge every
+/// future build into a silent no-op.
+struct BuildLock {
+    path: std::path::PathBuf,
+}
+
+impl BuildLock {
+    fn acquire(path: std::path::PathBuf) -> io::Result<Option<BuildLock>> {
+        if Self::try_create(&path)? {
+            return Ok(Some(BuildLock { path }));
+        }
+        if Self::is_stale(&path)? && Self::claim_stale(&path)? && Self::try_create(&path)? {
+            return Ok(Some(BuildLock { path }));
+        }
+        Ok(None)
+    }
+
+    fn try_create(path: &std::path::Path) -> io::Result<bool> {
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+        {
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn is_stale(path: &std::path::Path) -> io::Result<bool> {
+        match fs::metadata(path) {
+            Ok(metadata) => {
+                let age = metadata
+                    .modified()?
+                    .elapsed()
+                    .unwrap_or(std::time::Duration::from_secs(0));
+                Ok(age > STALE_LOCK_AGE)
+            }
+            // The lock vanished between our failed create and now (e.g. its
+            // holder just finished); treat that as reclaimable too.
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(true),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Claim a stale lock for reclaiming by renaming it aside. `rename` is
+    /// atomic, so of however many processes spot the same stale lock and
+    /// race here, exactly one observes `Ok` (and goes on to recreate the
+    /// lock fresh); the rest find the source already gone and back off,
+    /// rather than all recreating the path and each believing they hold it.
+    fn claim_stale(path: &std::path::Path) -> io::Result<bool> {
+        let claimed_path = path.with_extension("stale");
+        match fs::rename(path, &claimed_path) {
+            Ok(()) => {
+                let _ = fs::remove_file(&claimed_path);
+                Ok(true)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl Drop for BuildLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn treats_a_fresh_lock_as_not_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("build.lock");
+        fs::File::create(&path).unwrap();
+
+        assert!(!BuildLock::is_stale(&path).unwrap());
+    }
+
+    #[test]
+    fn treats_an_old_lock_as_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("build.lock");
+        fs::File::create(&path).unwrap();
+        let old =
+            std::time::SystemTime::now() - STALE_LOCK_AGE - std::time::Duration::from_secs(1);
+        fs::File::open(&path).unwrap().set_modified(old).unwrap();
+
+        assert!(BuildLock::is_stale(&path).unwrap());
+    }
+
+    #[test]
+    fn treats_a_missing_lock_as_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("build.lock");
+
+        assert!(BuildLock::is_stale(&path).unwrap());
     }
 }
@@ -0,0 +1,53 @@
+use crate::config;
+use std::fmt;
+use std::io;
+
+pub const NAME: &str = "clean";
+
+type Result = std::result::Result<u8, Error>;
+
+pub enum Error {
+    Config(config::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+        match self {
+            Config(err) => write!(f, "{}", err),
+            Io(err) => write!(f, "input/output error: {}", err),
+        }
+    }
+}
+
+impl From<config::Error> for Error {
+    fn from(error: config::Error) -> Self {
+        Error::Config(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+pub fn argspec<'a, 'b>() -> clap::App<'a, 'b> {
+    clap::SubCommand::with_name(NAME)
+        .about("Removes the cached development environment")
+        .arg(
+            clap::Arg::with_name("dir")
+                .value_name("DIR")
+                .help("The directory to clean"),
+        )
+}
+
+pub fn run(args: &clap::ArgMatches) -> Result {
+    let config = config::Config::load(args.value_of_os("dir"))?;
+    if config.cache_dir.exists() {
+        log::info!("Remove cache dir at {:?}.", &config.cache_dir);
+        std::fs::remove_dir_all(&config.cache_dir)?;
+    }
+    Ok(0)
+}
@@ -0,0 +1,53 @@
+use std::env::vars_os;
+use std::fmt;
+use std::fs;
+use std::io;
+
+pub const NAME: &str = "env";
+
+type Result = std::result::Result<u8, Error>;
+
+pub enum Error {
+    Io(io::Error),
+    Encode(bincode::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+        match self {
+            Io(err) => write!(f, "input/output error: {}", err),
+            Encode(err) => write!(f, "could not encode environment: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+/// Not user-facing: `config::Config` shells out to `firstaide env --dump
+/// <path>` to capture the environment outside the project, and the direnv
+/// backend runs it via `direnv exec` to capture the environment inside, since Rust can't easily read another
+/// process's environment directly.
+pub fn argspec<'a, 'b>() -> clap::App<'a, 'b> {
+    clap::SubCommand::with_name(NAME)
+        .about("Dumps the current environment to a file (internal use)")
+        .arg(
+            clap::Arg::with_name("dump")
+                .long("dump")
+                .value_name("PATH")
+                .required(true)
+                .help("Where to write the encoded environment"),
+        )
+}
+
+pub fn run(args: &clap::ArgMatches) -> Result {
+    let dump_path = args.value_of_os("dump").expect("--dump is required");
+    let env: crate::env::Env = vars_os().collect();
+    let bytes = bincode::serialize(&env).map_err(Error::Encode)?;
+    fs::write(dump_path, bytes)?;
+    Ok(0)
+}
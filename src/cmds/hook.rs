@@ -9,6 +9,7 @@ use std::ffi::OsString;
 use std::fmt;
 use std::fs;
 use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
 use tempfile;
 
 pub const NAME: &str = "hook";
@@ -58,6 +59,7 @@ pub fn argspec<'a, 'b>() -> clap::App<'a, 'b> {
 
 pub fn run(args: &clap::ArgMatches) -> Result {
     let config = config::Config::load(args.value_of_os("dir"))?;
+    let backend = config.backend();
     let stdout = io::stdout();
     let mut handle = stdout.lock();
 
@@ -87,6 +89,40 @@ pub fn run(args: &clap::ArgMatches) -> Result {
         out
     }
 
+    fn write_stale(
+        handle: &mut dyn Write,
+        config: &config::Config,
+        cache: &cache::Cache,
+        backend_prefixes: &[&'static [u8]],
+    ) -> io::Result<()> {
+        let stale_chunk = match config.refresh {
+            config::Refresh::Blocking => include_bytes!("hook/stale.sh").to_vec(),
+            config::Refresh::Background => {
+                let firstaide_exe = std::env::current_exe()?;
+                let log_path = config.cache_dir.join("build.log");
+                include_bytes!("hook/stale-background.sh")
+                    .replace(b"__FIRSTAIDE_EXE__", crate::bash::escape(&firstaide_exe))
+                    .replace(b"__BUILD_DIR__", crate::bash::escape(&config.build_dir))
+                    .replace(b"__LOG_PATH__", crate::bash::escape(&log_path))
+            }
+        };
+        handle.write_all(&chunk(&EnvironmentStatus::Stale.display(), &stale_chunk))?;
+        handle.write_all(&chunk(
+            "Cached environment follows:",
+            &env_diff_dump(&cache.diff, backend_prefixes),
+        ))
+    }
+
+    fn write_watches(handle: &mut dyn Write, cache: &cache::Cache) -> io::Result<()> {
+        let watches: Vec<u8> = cache
+            .sums
+            .clone()
+            .into_iter()
+            .flat_map(|sum| watch(sum.path()))
+            .collect();
+        handle.write_all(&chunk("Watch dependencies.", &watches))
+    }
+
     // Setting up additional OS pipes for subprocesses to communicate back to us
     // is not well supported in the Rust standard library, so we use files in a
     // temporary directory instead.
@@ -106,11 +142,16 @@ pub fn run(args: &clap::ArgMatches) -> Result {
         }
     }?;
 
+    let backend_prefixes = backend.env_prefixes_to_exclude();
+
     let env: env::Env = vars_os().collect();
 
-    let mut diff = env::diff(&env, &env_outside).exclude_by_prefix(b"DIRENV_");
+    let mut diff = env::diff(&env, &env_outside).exclude_by_prefixes(&backend_prefixes);
 
-    let watches = env_outside.iter().find(|(key, _)| key == "DIRENV_WATCHES");
+    let vars_to_preserve = backend.vars_to_preserve();
+    let watches = env_outside
+        .iter()
+        .find(|(key, _)| vars_to_preserve.iter().any(|name| key.as_bytes() == *name));
 
     if let Some((key, value)) = watches {
         diff.push(env::Added(key.clone(), value.clone()));
@@ -118,62 +159,70 @@ pub fn run(args: &clap::ArgMatches) -> Result {
 
     handle.write_all(&chunk(
         "Parent environment follows:",
-        &env_diff_dump(&diff),
+        &env_diff_dump(&diff, &backend_prefixes),
     ))?;
 
     handle.write_all(&chunk("Helpers.", include_bytes!("hook/helpers.sh")))?;
 
-    match cache::Cache::load(config.cache_file()) {
-        Ok(cache) => {
-            let sums_now = sums::Checksums::from(&config.watch_files()?)?;
-            if sums::equal(&sums_now, &cache.sums) {
-                let chunk_message = crate::bash::escape(&config.messages.getting_started);
-                let chunk_content =
-                    include_bytes!("hook/active.sh").replace(b"__MESSAGE__", chunk_message);
-                handle.write_all(&chunk(&EnvironmentStatus::Okay.display(), &chunk_content))?;
-                handle.write_all(&chunk(
-                    "Cached environment follows:",
-                    &env_diff_dump(&cache.diff),
-                ))?;
-            } else {
-                handle.write_all(&chunk(
-                    &EnvironmentStatus::Stale.display(),
-                    include_bytes!("hook/stale.sh"),
-                ))?;
-                handle.write_all(&chunk(
-                    "Cached environment follows:",
-                    &env_diff_dump(&cache.diff),
-                ))?;
-            }
-            let watches = cache.sums.into_iter().map(|sum| watch(sum.path()));
+    // The key is a fingerprint of the watched files' current contents, so an
+    // exact hit here means the cache matches the tree right now -- no need
+    // to separately recompare checksums the way a single-entry cache would.
+    let sums_now = sums::Checksums::from(&config.watch_files()?)?;
+    let key = sums_now.key();
+    let entry_path = config.cache_entry(&key);
+
+    match cache::Cache::load(&entry_path) {
+        Ok(cache) if cache.is_within_ttl(config.cache_ttl) => {
+            let chunk_message = crate::bash::escape(&config.messages.getting_started);
+            let chunk_content =
+                include_bytes!("hook/active.sh").replace(b"__MESSAGE__", chunk_message);
+            handle.write_all(&chunk(&EnvironmentStatus::Okay.display(), &chunk_content))?;
             handle.write_all(&chunk(
-                "Watch dependencies.",
-                &watches.flatten().collect::<Vec<u8>>(),
+                "Cached environment follows:",
+                &env_diff_dump(&cache.diff, &backend_prefixes),
             ))?;
+            write_watches(&mut handle, &cache)?;
         }
-        Err(_) => {
-            handle.write_all(&chunk(
-                &EnvironmentStatus::Unknown.display(),
-                include_bytes!("hook/inactive.sh"),
-            ))?;
+        Ok(cache) => {
+            // An exact checksum match that's simply aged out of its TTL.
+            write_stale(&mut handle, &config, &cache, &backend_prefixes)?;
+            write_watches(&mut handle, &cache)?;
         }
+        Err(_) => match cache::most_recently_used(&config.cache_envs_dir()) {
+            // No entry for the current tree, but we have a last-known-good
+            // environment from a previous state (e.g. another branch) to
+            // fall back on while a rebuild happens.
+            Some(cache) => {
+                write_stale(&mut handle, &config, &cache, &backend_prefixes)?;
+                write_watches(&mut handle, &cache)?;
+            }
+            None => {
+                handle.write_all(&chunk(
+                    &EnvironmentStatus::Unknown.display(),
+                    include_bytes!("hook/inactive.sh"),
+                ))?;
+            }
+        },
     };
 
-    handle.write_all(&chunk("Watch the cache file.", &watch(config.cache_file())))?;
+    handle.write_all(&chunk("Watch the cache entry.", &watch(entry_path)))?;
 
     writeln!(&mut handle, "}} # End.")?;
 
     Ok(0)
 }
 
-pub fn env_diff_dump(diff: &env::Diff) -> Vec<u8> {
+pub fn env_diff_dump(diff: &env::Diff, backend_prefixes: &[&'static [u8]]) -> Vec<u8> {
     use crate::bash::escape as esc;
     use crate::env::Change::*;
 
-    // Filter out DIRENV_ and SSH_ vars.
-    let diff = diff
-        .exclude_by_prefix(b"DIRENV_")
-        .exclude_by_prefix(b"SSH_");
+    // SSH_ is excluded unconditionally (agent/auth plumbing no backend
+    // should ever need to export); the backend's own internal prefixes
+    // (e.g. direnv's DIRENV_) come from `backend_prefixes` instead of being
+    // hardcoded here, so a non-direnv backend can't leak its internals.
+    let mut prefixes: Vec<&[u8]> = vec![b"SSH_"];
+    prefixes.extend_from_slice(backend_prefixes);
+    let diff = diff.exclude_by_prefixes(&prefixes);
 
     let mut output: Vec<u8> = Vec::new();
     for change in &diff {
@@ -0,0 +1,5 @@
+pub mod build;
+pub mod clean;
+pub mod env;
+pub mod hook;
+pub mod status;
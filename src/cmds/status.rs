@@ -0,0 +1,66 @@
+use crate::cache;
+use crate::config;
+use crate::status::EnvironmentStatus;
+use crate::sums;
+use std::fmt;
+use std::io;
+
+pub const NAME: &str = "status";
+
+type Result = std::result::Result<u8, Error>;
+
+pub enum Error {
+    Config(config::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+        match self {
+            Config(err) => write!(f, "{}", err),
+            Io(err) => write!(f, "input/output error: {}", err),
+        }
+    }
+}
+
+impl From<config::Error> for Error {
+    fn from(error: config::Error) -> Self {
+        Error::Config(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+pub fn argspec<'a, 'b>() -> clap::App<'a, 'b> {
+    clap::SubCommand::with_name(NAME)
+        .about("Reports whether the development environment is up to date")
+        .arg(
+            clap::Arg::with_name("dir")
+                .value_name("DIR")
+                .help("The directory to check"),
+        )
+}
+
+pub fn run(args: &clap::ArgMatches) -> Result {
+    let config = config::Config::load(args.value_of_os("dir"))?;
+
+    let sums_now = sums::Checksums::from(&config.watch_files()?)?;
+    let entry_path = config.cache_entry(&sums_now.key());
+
+    let status = match cache::Cache::load(entry_path) {
+        Ok(cache) if cache.is_within_ttl(config.cache_ttl) => EnvironmentStatus::Okay,
+        Ok(_) => EnvironmentStatus::Stale,
+        Err(_) if cache::most_recently_used(&config.cache_envs_dir()).is_some() => {
+            EnvironmentStatus::Stale
+        }
+        Err(_) => EnvironmentStatus::Unknown,
+    };
+
+    println!("{}", status.display());
+    Ok(0)
+}
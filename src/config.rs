@@ -0,0 +1,215 @@
+use crate::backend::{self, Backend};
+use serde::Deserialize;
+use std::env;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+pub const CONFIG_FILE_NAME: &str = "firstaide.toml";
+
+#[derive(Debug, Deserialize)]
+struct RawConfig {
+    #[serde(default)]
+    watch_files: Vec<PathBuf>,
+    #[serde(default)]
+    messages: Messages,
+    /// How long a cache stays valid before it's considered stale regardless
+    /// of whether the watched files have changed, e.g. `"24h"`. Absent means
+    /// a cache never expires on its own.
+    #[serde(default)]
+    cache_ttl: Option<String>,
+    /// What `hook` should do when it finds a stale cache.
+    #[serde(default)]
+    refresh: Refresh,
+    /// Which backend to use to prepare and capture the environment.
+    #[serde(default)]
+    backend: BackendKind,
+    /// How many built environments to keep around at once, keyed by the
+    /// checksums of the watched files that produced them. Older entries
+    /// beyond this limit are evicted least-recently-used first.
+    #[serde(default = "default_cache_entries")]
+    cache_entries: usize,
+}
+
+fn default_cache_entries() -> usize {
+    5
+}
+
+/// The backend implementations `firstaide` knows how to build. `direnv` is
+/// currently the only one, but this is where a `nix develop`/flake backend
+/// or a plain `source script` backend would be added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    Direnv,
+}
+
+impl Default for BackendKind {
+    fn default() -> Self {
+        BackendKind::Direnv
+    }
+}
+
+/// How `hook` responds to a stale cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Refresh {
+    /// Tell the user to run `firstaide build` themselves; keep exporting the
+    /// last-known-good environment in the meantime.
+    Blocking,
+    /// Export the last-known-good environment and kick off a `firstaide
+    /// build` in the background, so the next shell reload picks up the
+    /// refreshed cache.
+    Background,
+}
+
+impl Default for Refresh {
+    fn default() -> Self {
+        Refresh::Blocking
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Messages {
+    #[serde(default = "default_getting_started")]
+    pub getting_started: String,
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Messages {
+            getting_started: default_getting_started(),
+        }
+    }
+}
+
+fn default_getting_started() -> String {
+    "Building your development environment...".into()
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub build_dir: PathBuf,
+    pub cache_dir: PathBuf,
+    pub messages: Messages,
+    pub cache_ttl: Option<Duration>,
+    pub refresh: Refresh,
+    pub cache_entries: usize,
+    backend: BackendKind,
+    watch_files: Vec<PathBuf>,
+}
+
+pub enum Error {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    InvalidCacheTtl(String, humantime::DurationError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+        match self {
+            Io(err) => write!(f, "input/output error: {}", err),
+            Toml(err) => write!(f, "could not parse {}: {}", CONFIG_FILE_NAME, err),
+            InvalidCacheTtl(raw, err) => {
+                write!(f, "could not parse cache_ttl {:?}: {}", raw, err)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(error: toml::de::Error) -> Self {
+        Error::Toml(error)
+    }
+}
+
+impl Config {
+    pub fn load(dir: Option<&OsStr>) -> Result<Config, Error> {
+        let build_dir = match dir {
+            Some(dir) => PathBuf::from(dir),
+            None => env::current_dir()?,
+        };
+
+        let config_path = build_dir.join(CONFIG_FILE_NAME);
+        let raw: RawConfig = if config_path.exists() {
+            toml::from_str(&fs::read_to_string(&config_path)?)?
+        } else {
+            RawConfig {
+                watch_files: Vec::new(),
+                messages: Messages::default(),
+                cache_ttl: None,
+                refresh: Refresh::default(),
+                backend: BackendKind::default(),
+                cache_entries: default_cache_entries(),
+            }
+        };
+
+        let cache_ttl = raw
+            .cache_ttl
+            .map(|raw_ttl| {
+                humantime::parse_duration(&raw_ttl)
+                    .map_err(|err| Error::InvalidCacheTtl(raw_ttl, err))
+            })
+            .transpose()?;
+
+        Ok(Config {
+            cache_dir: build_dir.join(".firstaide"),
+            build_dir,
+            messages: raw.messages,
+            cache_ttl,
+            refresh: raw.refresh,
+            backend: raw.backend,
+            cache_entries: raw.cache_entries,
+            watch_files: raw.watch_files,
+        })
+    }
+
+    pub fn watch_files(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut files = self.watch_files.clone();
+        files.push(self.build_dir.join(".envrc"));
+        files.push(self.build_dir.join(CONFIG_FILE_NAME));
+        Ok(files)
+    }
+
+    /// The directory that holds one cache entry per distinct checksum key.
+    pub fn cache_envs_dir(&self) -> PathBuf {
+        self.cache_dir.join("envs")
+    }
+
+    /// The path of the cache entry for a given checksum key.
+    pub fn cache_entry(&self, key: &str) -> PathBuf {
+        self.cache_envs_dir().join(key)
+    }
+
+    pub fn command_to_dump_env_outside(&self, dump_path: &Path) -> Command {
+        let mut command = Command::new(current_exe());
+        command
+            .current_dir(&self.build_dir)
+            .arg(crate::cmds::env::NAME)
+            .arg("--dump")
+            .arg(dump_path);
+        command
+    }
+
+    /// The backend selected by the `backend` config key (`direnv` by default).
+    pub fn backend(&self) -> Box<dyn Backend> {
+        match self.backend {
+            BackendKind::Direnv => Box::new(backend::direnv::Direnv),
+        }
+    }
+}
+
+fn current_exe() -> PathBuf {
+    env::current_exe().expect("could not determine path to the current executable")
+}
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+
+/// A captured set of environment variables, in the order they were observed.
+pub type Env = Vec<(OsString, OsString)>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Change {
+    Added(OsString, OsString),
+    Changed(OsString, OsString, OsString),
+    Removed(OsString, OsString),
+}
+pub use Change::{Added, Changed, Removed};
+
+impl Change {
+    fn key(&self) -> &OsStr {
+        match self {
+            Change::Added(k, _) | Change::Changed(k, _, _) | Change::Removed(k, _) => k,
+        }
+    }
+}
+
+/// The set of changes needed to turn one [`Env`] into another.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Diff(Vec<Change>);
+
+impl Diff {
+    pub fn push(&mut self, change: Change) {
+        self.0.push(change);
+    }
+
+    /// A copy of this diff with any change whose key starts with `prefix` removed.
+    pub fn exclude_by_prefix(&self, prefix: &[u8]) -> Diff {
+        Diff(
+            self.0
+                .iter()
+                .filter(|change| !change.key().as_bytes().starts_with(prefix))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// A copy of this diff with any change whose key starts with one of
+    /// `prefixes` removed.
+    pub fn exclude_by_prefixes(&self, prefixes: &[&[u8]]) -> Diff {
+        Diff(
+            self.0
+                .iter()
+                .filter(|change| {
+                    let key = change.key().as_bytes();
+                    !prefixes.iter().any(|prefix| key.starts_with(prefix))
+                })
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+impl<'a> IntoIterator for &'a Diff {
+    type Item = &'a Change;
+    type IntoIter = std::slice::Iter<'a, Change>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// Compute the changes needed to turn `before` into `after`.
+pub fn diff(before: &Env, after: &Env) -> Diff {
+    let mut changes = Vec::new();
+    for (key, value_after) in after {
+        match before.iter().find(|(k, _)| k == key) {
+            None => changes.push(Change::Added(key.clone(), value_after.clone())),
+            Some((_, value_before)) if value_before != value_after => changes.push(
+                Change::Changed(key.clone(), value_before.clone(), value_after.clone()),
+            ),
+            Some(_) => {}
+        }
+    }
+    for (key, value_before) in before {
+        if !after.iter().any(|(k, _)| k == key) {
+            changes.push(Change::Removed(key.clone(), value_before.clone()));
+        }
+    }
+    Diff(changes)
+}
@@ -0,0 +1,25 @@
+use crate::cmds;
+use std::fmt;
+
+pub enum Error {
+    BuildError(cmds::build::Error),
+    StatusError(cmds::status::Error),
+    CleanError(cmds::clean::Error),
+    HookError(cmds::hook::Error),
+    EnvError(cmds::env::Error),
+    CommandNotFound(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+        match self {
+            BuildError(err) => write!(f, "{}", err),
+            StatusError(err) => write!(f, "{}", err),
+            CleanError(err) => write!(f, "{}", err),
+            HookError(err) => write!(f, "{}", err),
+            EnvError(err) => write!(f, "{}", err),
+            CommandNotFound(name) => write!(f, "no such command: {}", name),
+        }
+    }
+}
@@ -4,6 +4,7 @@ extern crate clap;
 use fern;
 use std::process;
 
+mod backend;
 mod bash;
 mod cache;
 mod cmds;
@@ -0,0 +1,20 @@
+/// The state the hook (and the `status` command) reports for the current
+/// environment, based on whether a cache exists and whether it's still fresh.
+pub enum EnvironmentStatus {
+    /// A cache exists and matches the current state of the watched files.
+    Okay,
+    /// A cache exists but is out of date.
+    Stale,
+    /// No cache exists yet; the environment has never been built.
+    Unknown,
+}
+
+impl EnvironmentStatus {
+    pub fn display(&self) -> String {
+        match self {
+            EnvironmentStatus::Okay => "Environment up to date.".into(),
+            EnvironmentStatus::Stale => "Environment stale.".into(),
+            EnvironmentStatus::Unknown => "Environment not built yet.".into(),
+        }
+    }
+}
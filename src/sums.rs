@@ -0,0 +1,88 @@
+use crate::config;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checksum {
+    path: PathBuf,
+    hash: [u8; 32],
+}
+
+impl Checksum {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// The checksums of a set of watched files, used to decide whether a cached
+/// environment is still valid for the current state of the tree.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Checksums(Vec<Checksum>);
+
+impl Checksums {
+    pub fn from(paths: &[PathBuf]) -> Result<Checksums, config::Error> {
+        let mut sums = Vec::with_capacity(paths.len());
+        for path in paths {
+            let contents = fs::read(path).map_err(config::Error::Io)?;
+            sums.push(Checksum {
+                path: path.clone(),
+                hash: Sha256::digest(&contents).into(),
+            });
+        }
+        Ok(Checksums(sums))
+    }
+
+    /// A stable fingerprint of this set of checksums, used as the cache key
+    /// for the current state of the tree: two trees with the same watched
+    /// file contents always produce the same key, regardless of the order
+    /// the files were hashed in.
+    pub fn key(&self) -> String {
+        let mut sums = self.0.clone();
+        sums.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut hasher = Sha256::new();
+        for sum in &sums {
+            hasher.update(sum.path.as_os_str().as_bytes());
+            hasher.update(b"\0");
+            hasher.update(sum.hash);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+impl IntoIterator for Checksums {
+    type Item = Checksum;
+    type IntoIter = std::vec::IntoIter<Checksum>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checksum(path: &str, byte: u8) -> Checksum {
+        Checksum {
+            path: PathBuf::from(path),
+            hash: [byte; 32],
+        }
+    }
+
+    #[test]
+    fn key_is_independent_of_input_order() {
+        let a = Checksums(vec![checksum("a", 1), checksum("b", 2)]);
+        let b = Checksums(vec![checksum("b", 2), checksum("a", 1)]);
+        assert_eq!(a.key(), b.key());
+    }
+
+    #[test]
+    fn key_changes_when_a_hash_changes() {
+        let a = Checksums(vec![checksum("a", 1)]);
+        let b = Checksums(vec![checksum("a", 2)]);
+        assert_ne!(a.key(), b.key());
+    }
+}